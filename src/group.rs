@@ -0,0 +1,170 @@
+//! This module provides a wrapper around the libc functions in `grp.h` for
+//! handling the `/etc/group` file, which stores information about groups.
+//!
+//! # Examples
+//!
+//! ```
+//! use passwd::Group;
+//!
+//! println!("{:?}", Group::from_name("root"));
+//! println!("{:?}", Group::from_gid(0));
+//! ```
+
+use std::ffi::CString;
+use std::ffi::CStr;
+use std::ffi::OsString;
+use std::io;
+use std::os::unix::ffi::OsStrExt;
+use std::sync::{Mutex, MutexGuard};
+
+use crate::reentrant;
+
+/// Represents an entry in `/etc/group`
+///
+/// `name` and `members` are assumed to be ASCII-ish, same as `Passwd::name`,
+/// and panic on construction if they aren't valid UTF-8. `password`, which
+/// carries no such assumption, is exposed losslessly as `OsString`.
+#[derive(Debug)]
+pub struct Group {
+    /// group name
+    pub name: String,
+    /// group password
+    pub password: OsString,
+    /// group ID
+    pub gid: libc::gid_t,
+    /// usernames of the group's members
+    pub members: Vec<String>,
+}
+
+impl Group {
+    unsafe fn from_ptr(grp: *const libc::group) -> Group {
+        let mut members = Vec::new();
+        let mut i = 0;
+        loop {
+            let member = *(*grp).gr_mem.offset(i);
+            if member.is_null() {
+                break;
+            }
+            members.push(CStr::from_ptr(member).to_str().unwrap().to_owned());
+            i += 1;
+        }
+
+        Group {
+            name: CStr::from_ptr((*grp).gr_name).to_str().unwrap().to_owned(),
+            password: OsString::from(std::ffi::OsStr::from_bytes(CStr::from_ptr((*grp).gr_passwd).to_bytes())),
+            gid: (*grp).gr_gid,
+            members,
+        }
+    }
+
+    /// Gets a `Group` entry for the given group name.
+    ///
+    /// Returns `Ok(None)` if there is no such group, and `Err` if the
+    /// underlying `getgrnam_r()` call fails.
+    pub fn from_name(name: &str) -> io::Result<Option<Group>> {
+        let c_name = CString::new(name).unwrap();
+
+        let mut grp: libc::group = unsafe { std::mem::zeroed() };
+        let (ret, group) = reentrant::with_growing_buf(
+            getgr_r_size_max(),
+            |buf| unsafe {
+                let mut result = std::ptr::null_mut();
+                let ret = libc::getgrnam_r(c_name.as_ptr(),
+                                            &mut grp,
+                                            buf.as_mut_ptr(),
+                                            buf.capacity(),
+                                            &mut result);
+                (ret, result)
+            },
+            |result| unsafe { Group::from_ptr(result) },
+        );
+
+        if ret != 0 {
+            Err(io::Error::from_raw_os_error(ret))
+        } else {
+            Ok(group)
+        }
+    }
+
+    /// Gets a `Group` entry for the given gid.
+    ///
+    /// Returns `Ok(None)` if there is no such group, and `Err` if the
+    /// underlying `getgrgid_r()` call fails.
+    pub fn from_gid(gid: libc::gid_t) -> io::Result<Option<Group>> {
+        let mut grp: libc::group = unsafe { std::mem::zeroed() };
+        let (ret, group) = reentrant::with_growing_buf(
+            getgr_r_size_max(),
+            |buf| unsafe {
+                let mut result = std::ptr::null_mut();
+                let ret = libc::getgrgid_r(gid, &mut grp, buf.as_mut_ptr(), buf.capacity(), &mut result);
+                (ret, result)
+            },
+            |result| unsafe { Group::from_ptr(result) },
+        );
+
+        if ret != 0 {
+            Err(io::Error::from_raw_os_error(ret))
+        } else {
+            Ok(group)
+        }
+    }
+
+    /// Returns an iterator over every entry in `/etc/group`.
+    ///
+    /// This wraps `setgrent()`/`getgrent()`/`endgrent()`. Since `getgrent()`
+    /// relies on global state shared across the whole process, the iterator
+    /// holds a process-wide lock for as long as it is alive, so concurrent
+    /// calls to `all()` from multiple threads can't interleave and corrupt
+    /// each other.
+    pub fn all() -> GroupIter {
+        GroupIter::new()
+    }
+}
+
+static GRENT_LOCK: Mutex<()> = Mutex::new(());
+
+/// Iterator over every entry in `/etc/group`, created by `Group::all()`.
+pub struct GroupIter {
+    _guard: MutexGuard<'static, ()>,
+}
+
+impl GroupIter {
+    fn new() -> GroupIter {
+        let guard = GRENT_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        unsafe {
+            libc::setgrent();
+        }
+        GroupIter { _guard: guard }
+    }
+}
+
+impl Iterator for GroupIter {
+    type Item = Group;
+
+    fn next(&mut self) -> Option<Group> {
+        let result = unsafe { libc::getgrent() };
+
+        if result.is_null() {
+            None
+        } else {
+            Some(unsafe { Group::from_ptr(result) })
+        }
+    }
+}
+
+impl Drop for GroupIter {
+    fn drop(&mut self) {
+        unsafe {
+            libc::endgrent();
+        }
+    }
+}
+
+fn getgr_r_size_max() -> usize {
+    // Borrowed from libstd/sys/unix/os.rs
+    // (As are a few lines elsewhere)
+    match unsafe { libc::sysconf(libc::_SC_GETGR_R_SIZE_MAX) } {
+        n if n < 0 => 512_usize,
+        n => n as usize,
+    }
+}
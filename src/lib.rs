@@ -16,6 +16,15 @@ extern crate libc;
 
 use std::ffi::CString;
 use std::ffi::CStr;
+use std::ffi::OsString;
+use std::io;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::ffi::OsStringExt;
+use std::sync::{Mutex, MutexGuard};
+
+mod group;
+mod reentrant;
+pub use group::{Group, GroupIter};
 
 
 /// Represents an entry in `/etc/passwd`
@@ -24,67 +33,211 @@ pub struct Passwd {
     /// username
     pub name: String,
     /// user password
-    pub password: String,
+    pub password: OsString,
     /// user ID
     pub uid: libc::uid_t,
     /// group ID
     pub gid: libc::gid_t,
     /// user information
-    pub gecos: String,
+    pub gecos: OsString,
     /// home directory
-    pub home_dir: String,
+    pub home_dir: OsString,
     /// shell program
-    pub shell: String,
+    pub shell: OsString,
 }
 
 impl Passwd {
     unsafe fn from_ptr(pwd: *const libc::passwd) -> Passwd {
         Passwd {
             name: CStr::from_ptr((*pwd).pw_name).to_str().unwrap().to_owned(),
-            password: CStr::from_ptr((*pwd).pw_passwd).to_str().unwrap().to_owned(),
+            password: OsString::from(std::ffi::OsStr::from_bytes(CStr::from_ptr((*pwd).pw_passwd).to_bytes())),
             uid: (*pwd).pw_uid,
             gid: (*pwd).pw_gid,
 
             #[cfg(not(target_os = "android"))]
-            gecos: CStr::from_ptr((*pwd).pw_gecos).to_str().unwrap().to_owned(),
+            gecos: OsString::from(std::ffi::OsStr::from_bytes(CStr::from_ptr((*pwd).pw_gecos).to_bytes())),
             #[cfg(target_os = "android")]
-            gecos: String::new(),
+            gecos: OsString::new(),
 
-            home_dir: CStr::from_ptr((*pwd).pw_dir).to_str().unwrap().to_owned(),
-            shell: CStr::from_ptr((*pwd).pw_shell).to_str().unwrap().to_owned(),
+            home_dir: OsString::from(std::ffi::OsStr::from_bytes(CStr::from_ptr((*pwd).pw_dir).to_bytes())),
+            shell: OsString::from(std::ffi::OsStr::from_bytes(CStr::from_ptr((*pwd).pw_shell).to_bytes())),
         }
     }
 
-    /// Gets a `Passwd` entry for the given username, or returns `None`
-    pub fn from_name(user: &str) -> Option<Passwd> {
+    /// Gets a `Passwd` entry for the given username.
+    ///
+    /// Returns `Ok(None)` if there is no such user, and `Err` if the
+    /// underlying `getpwnam_r()` call fails.
+    pub fn from_name(user: &str) -> io::Result<Option<Passwd>> {
         let c_user = CString::new(user).unwrap();
 
         let mut pwd: libc::passwd = unsafe { std::mem::zeroed() };
-        let mut buf = Vec::with_capacity(getpw_r_size_max());
-        let mut result = std::ptr::null_mut();
-        unsafe {
-            libc::getpwnam_r(c_user.as_ptr(),
-                             &mut pwd,
-                             buf.as_mut_ptr(),
-                             buf.capacity(),
-                             &mut result);
-        }
+        let (ret, passwd) = reentrant::with_growing_buf(
+            getpw_r_size_max(),
+            |buf| unsafe {
+                let mut result = std::ptr::null_mut();
+                let ret = libc::getpwnam_r(c_user.as_ptr(),
+                                            &mut pwd,
+                                            buf.as_mut_ptr(),
+                                            buf.capacity(),
+                                            &mut result);
+                (ret, result)
+            },
+            |result| unsafe { Passwd::from_ptr(result) },
+        );
 
-        if result.is_null() {
-            None
+        if ret != 0 {
+            Err(io::Error::from_raw_os_error(ret))
         } else {
-            Some(unsafe { Passwd::from_ptr(result) })
+            Ok(passwd)
         }
     }
 
-    /// Gets a `Passwd` entry for the given uid, or returns `None`
-    pub fn from_uid(uid: libc::uid_t) -> Option<Passwd> {
+    /// Gets a `Passwd` entry for the given uid.
+    ///
+    /// Returns `Ok(None)` if there is no such user, and `Err` if the
+    /// underlying `getpwuid_r()` call fails.
+    pub fn from_uid(uid: libc::uid_t) -> io::Result<Option<Passwd>> {
         let mut pwd: libc::passwd = unsafe { std::mem::zeroed() };
-        let mut buf = Vec::with_capacity(getpw_r_size_max());
-        let mut result = std::ptr::null_mut();
+        let (ret, passwd) = reentrant::with_growing_buf(
+            getpw_r_size_max(),
+            |buf| unsafe {
+                let mut result = std::ptr::null_mut();
+                let ret = libc::getpwuid_r(uid, &mut pwd, buf.as_mut_ptr(), buf.capacity(), &mut result);
+                (ret, result)
+            },
+            |result| unsafe { Passwd::from_ptr(result) },
+        );
+
+        if ret != 0 {
+            Err(io::Error::from_raw_os_error(ret))
+        } else {
+            Ok(passwd)
+        }
+    }
+
+    /// Gets the `Passwd` entry for the real user id of the calling process
+    pub fn current_user() -> io::Result<Option<Passwd>> {
+        Passwd::from_uid(unsafe { libc::getuid() })
+    }
+
+    /// Gets the `Passwd` entry for the effective user id of the calling process
+    pub fn effective_user() -> io::Result<Option<Passwd>> {
+        Passwd::from_uid(unsafe { libc::geteuid() })
+    }
+
+    /// Gets the IDs of every group `self` belongs to, including its primary
+    /// group, via `getgrouplist()`.
+    pub fn groups(&self) -> io::Result<Vec<libc::gid_t>> {
+        let c_name = CString::new(self.name.clone()).unwrap();
+        let mut ngroups: libc::c_int = 16;
+
+        loop {
+            let mut groups: Vec<libc::gid_t> = Vec::with_capacity(ngroups as usize);
+            let ret = unsafe {
+                libc::getgrouplist(c_name.as_ptr(), self.gid, groups.as_mut_ptr(), &mut ngroups)
+            };
+
+            if ret == -1 {
+                if ngroups as usize > MAX_GROUPS {
+                    return Err(io::Error::from_raw_os_error(libc::ERANGE));
+                }
+                continue;
+            }
+
+            unsafe { groups.set_len(ngroups as usize) };
+            return Ok(groups);
+        }
+    }
+
+    /// Returns an iterator over every entry in `/etc/passwd`.
+    ///
+    /// This wraps `setpwent()`/`getpwent()`/`endpwent()`. Since `getpwent()`
+    /// relies on global state shared across the whole process, the iterator
+    /// holds a process-wide lock for as long as it is alive, so concurrent
+    /// calls to `all()` from multiple threads can't interleave and corrupt
+    /// each other.
+    pub fn all() -> PasswdIter {
+        PasswdIter::new()
+    }
+}
+
+/// An owned `libc::passwd`, along with the `CString`s that back its pointer
+/// fields, produced by converting a `Passwd` for use with C APIs that expect
+/// a `struct passwd *`.
+pub struct PasswdC {
+    /// the `libc::passwd`; its pointer fields point into the `CString`s
+    /// below, which must outlive it
+    pub passwd: libc::passwd,
+
+    // Never read directly; these just need to stay alive as long as
+    // `passwd`'s pointers point into them.
+    #[allow(dead_code)]
+    name: CString,
+    #[allow(dead_code)]
+    password: CString,
+    #[allow(dead_code)]
+    gecos: CString,
+    #[allow(dead_code)]
+    home_dir: CString,
+    #[allow(dead_code)]
+    shell: CString,
+}
+
+impl From<Passwd> for PasswdC {
+    fn from(p: Passwd) -> PasswdC {
+        let name = CString::new(p.name).unwrap();
+        let password = CString::new(p.password.into_vec()).unwrap();
+        let gecos = CString::new(p.gecos.into_vec()).unwrap();
+        let home_dir = CString::new(p.home_dir.into_vec()).unwrap();
+        let shell = CString::new(p.shell.into_vec()).unwrap();
+
+        let passwd = libc::passwd {
+            pw_name: name.as_ptr() as *mut _,
+            pw_passwd: password.as_ptr() as *mut _,
+            pw_uid: p.uid,
+            pw_gid: p.gid,
+
+            #[cfg(not(target_os = "android"))]
+            pw_gecos: gecos.as_ptr() as *mut _,
+
+            pw_dir: home_dir.as_ptr() as *mut _,
+            pw_shell: shell.as_ptr() as *mut _,
+        };
+
+        PasswdC {
+            passwd,
+            name,
+            password,
+            gecos,
+            home_dir,
+            shell,
+        }
+    }
+}
+
+static PWENT_LOCK: Mutex<()> = Mutex::new(());
+
+/// Iterator over every entry in `/etc/passwd`, created by `Passwd::all()`.
+pub struct PasswdIter {
+    _guard: MutexGuard<'static, ()>,
+}
+
+impl PasswdIter {
+    fn new() -> PasswdIter {
+        let guard = PWENT_LOCK.lock().unwrap_or_else(|e| e.into_inner());
         unsafe {
-            libc::getpwuid_r(uid, &mut pwd, buf.as_mut_ptr(), buf.capacity(), &mut result);
+            libc::setpwent();
         }
+        PasswdIter { _guard: guard }
+    }
+}
+
+impl Iterator for PasswdIter {
+    type Item = Passwd;
+
+    fn next(&mut self) -> Option<Passwd> {
+        let result = unsafe { libc::getpwent() };
 
         if result.is_null() {
             None
@@ -94,11 +247,22 @@ impl Passwd {
     }
 }
 
+impl Drop for PasswdIter {
+    fn drop(&mut self) {
+        unsafe {
+            libc::endpwent();
+        }
+    }
+}
+
+/// Largest group list we'll grow `groups()`'s buffer to before giving up.
+const MAX_GROUPS: usize = 64 * 1024;
+
 fn getpw_r_size_max() -> usize {
     // Borrowed from libstd/sys/unix/os.rs
     // (As are a few lines elsewhere)
     match unsafe { libc::sysconf(libc::_SC_GETPW_R_SIZE_MAX) } {
-        n if n < 0 => 512 as usize,
+        n if n < 0 => 512_usize,
         n => n as usize,
     }
 }
@@ -0,0 +1,39 @@
+//! Shared helper for retrying the libc `_r` reentrant lookup functions
+//! (`getpwnam_r`, `getgrnam_r`, ...) with a growing buffer when they report
+//! `ERANGE`.
+
+/// Largest buffer we'll grow to before giving up on a `_r` call reporting
+/// `ERANGE`.
+pub(crate) const MAX_R_BUF_SIZE: usize = 64 * 1024;
+
+/// Calls `f` with a buffer starting at `cap`, doubling it and retrying
+/// while `f` reports `ERANGE`, up to `MAX_R_BUF_SIZE`.
+///
+/// The `_r` functions this wraps point their output struct's string fields
+/// into the caller-supplied buffer rather than copying them, so it must
+/// still be alive when those fields are read. `extract` is called with the
+/// result pointer while the buffer that backs it is still in scope, so it's
+/// the only safe place to copy the strings out; returns the final return
+/// code alongside whatever `extract` produced (or `None` if no entry was
+/// found).
+pub(crate) fn with_growing_buf<P, T, F, G>(mut cap: usize, mut f: F, mut extract: G) -> (libc::c_int, Option<T>)
+where
+    F: FnMut(&mut Vec<libc::c_char>) -> (libc::c_int, *mut P),
+    G: FnMut(*mut P) -> T,
+{
+    loop {
+        let mut buf = Vec::with_capacity(cap);
+        let (ret, result) = f(&mut buf);
+
+        if ret == libc::ERANGE && cap < MAX_R_BUF_SIZE {
+            cap *= 2;
+            continue;
+        }
+
+        if result.is_null() {
+            return (ret, None);
+        }
+
+        return (ret, Some(extract(result)));
+    }
+}